@@ -0,0 +1,164 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+/// Progress record for a single chunk persisted between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkRecord {
+    pub start: usize,
+    pub chunk_size: usize,
+    pub done: bool,
+}
+
+/// On-disk checkpoint describing a partially downloaded file.
+///
+/// It is serialized to a sidecar named after the staging file
+/// (`<filename>.part.mdpart.json`, see [`archive_path`]) so an interrupted download can resume the chunks it had not finished
+/// rather than restarting from zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadArchive {
+    pub total: usize,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub chunks: Vec<ChunkRecord>,
+}
+
+impl DownloadArchive {
+    /// Build a fresh archive for the given chunk layout, with every chunk
+    /// marked incomplete.
+    pub fn new(
+        total: usize,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        layout: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Self {
+        let chunks = layout
+            .into_iter()
+            .map(|(start, chunk_size)| ChunkRecord {
+                start,
+                chunk_size,
+                done: false,
+            })
+            .collect();
+        Self {
+            total,
+            etag,
+            last_modified,
+            chunks,
+        }
+    }
+
+    /// Whether the chunk starting at `start` has already been written and
+    /// `sync_all`'d in a previous run.
+    pub fn is_done(&self, start: usize) -> bool {
+        self.chunks
+            .iter()
+            .find(|c| c.start == start)
+            .map(|c| c.done)
+            .unwrap_or(false)
+    }
+
+    pub fn mark_done(&mut self, start: usize) {
+        if let Some(c) = self.chunks.iter_mut().find(|c| c.start == start) {
+            c.done = true;
+        }
+    }
+
+    pub fn all_done(&self) -> bool {
+        self.chunks.iter().all(|c| c.done)
+    }
+
+    /// Whether the archive still describes the remote resource. A changed
+    /// `Content-Length` means the stale state must be discarded and the
+    /// download restarted. When both sides carry an `ETag`, only the `ETag` is
+    /// compared; otherwise `Last-Modified` is compared when both carry one.
+    /// With neither validator on both sides, the length match is trusted.
+    pub fn matches(
+        &self,
+        total: usize,
+        etag: &Option<String>,
+        last_modified: &Option<String>,
+    ) -> bool {
+        if self.total != total {
+            return false;
+        }
+        if let (Some(a), Some(b)) = (&self.etag, etag) {
+            return a == b;
+        }
+        if let (Some(a), Some(b)) = (&self.last_modified, last_modified) {
+            return a == b;
+        }
+        true
+    }
+}
+
+/// Sidecar path for a download target (`foo.zip` -> `foo.zip.mdpart.json`).
+pub fn archive_path(target: &Path) -> PathBuf {
+    let mut s = OsString::from(target.as_os_str());
+    s.push(".mdpart.json");
+    PathBuf::from(s)
+}
+
+/// Load an existing archive, returning `None` if it is absent or unreadable.
+pub async fn load(path: &Path) -> Option<DownloadArchive> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Persist the archive atomically by writing a temp file and renaming it over
+/// the target, so a crash mid-write can never leave truncated state.
+pub async fn save_atomic(path: &Path, archive: &DownloadArchive) -> anyhow::Result<()> {
+    let bytes = serde_json::to_vec(archive)?;
+    let tmp = archive_path_tmp(path);
+    tokio::fs::write(&tmp, &bytes).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+fn archive_path_tmp(path: &Path) -> PathBuf {
+    let mut s = OsString::from(path.as_os_str());
+    s.push(".tmp");
+    PathBuf::from(s)
+}
+
+/// Shared handle threaded through the chunk layer so each finished chunk can
+/// checkpoint its progress atomically.
+#[derive(Clone)]
+pub struct ResumeState {
+    archive: Arc<Mutex<DownloadArchive>>,
+    path: PathBuf,
+}
+
+impl ResumeState {
+    pub fn new(archive: DownloadArchive, path: PathBuf) -> Self {
+        Self {
+            archive: Arc::new(Mutex::new(archive)),
+            path,
+        }
+    }
+
+    pub async fn is_done(&self, start: usize) -> bool {
+        self.archive.lock().await.is_done(start)
+    }
+
+    /// Mark the chunk complete and flush the updated archive to disk.
+    ///
+    /// The lock is held across the write so concurrent chunk completions
+    /// serialize: they share the single `<path>.tmp` staging file, and
+    /// interleaving two `save_atomic` calls could otherwise make one `rename`
+    /// fail with `ENOENT` or regress the persisted done-set.
+    pub async fn complete(&self, start: usize) -> anyhow::Result<()> {
+        let mut a = self.archive.lock().await;
+        a.mark_done(start);
+        save_atomic(&self.path, &a).await
+    }
+
+    /// Remove the sidecar once the download has been fully verified.
+    pub async fn finish(&self) -> anyhow::Result<()> {
+        let _ = tokio::fs::remove_file(&self.path).await;
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use futures_util::StreamExt;
 use indicatif::ProgressBar;
 use reqwest::header::RANGE;
 use reqwest_middleware::ClientWithMiddleware;
@@ -15,6 +16,20 @@ pub struct Chunk {
     pub(crate) chunk_size: usize,
 }
 
+/// Marker error raised when a ranged request comes back as a full `200` body:
+/// the server ignored `Range`, so the chunk plan must be abandoned in favor of
+/// a single-stream download.
+#[derive(Debug)]
+pub struct RangeIgnored;
+
+impl std::fmt::Display for RangeIgnored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "server ignored Range header, returned full 200 response")
+    }
+}
+
+impl std::error::Error for RangeIgnored {}
+
 impl Chunk {
     pub fn data(&self) -> &bytes::Bytes {
         &self.data
@@ -28,12 +43,35 @@ impl Chunk {
         self.chunk_size
     }
 
-    /// download one chunk from the url
+    /// download one chunk from the url, retrying a short or failed range read
+    /// up to `retry` extra times before giving up. A full `200` response is not
+    /// retried; it surfaces as [`RangeIgnored`] so the caller can fall back.
     pub async fn download(
         mut self,
         client: &ClientWithMiddleware,
         url: &str,
+        retry: usize,
     ) -> anyhow::Result<Self> {
+        let mut attempt = 0;
+        loop {
+            match self.download_once(client, url).await {
+                Ok(data) => {
+                    self.data = data;
+                    return anyhow::Ok(self);
+                }
+                Err(e) if e.downcast_ref::<RangeIgnored>().is_some() || attempt >= retry => {
+                    return Err(e);
+                }
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+
+    async fn download_once(
+        &self,
+        client: &ClientWithMiddleware,
+        url: &str,
+    ) -> anyhow::Result<bytes::Bytes> {
         let resp = client
             .get(url)
             .header(
@@ -42,16 +80,14 @@ impl Chunk {
             )
             .send()
             .await?;
+        if resp.status() == reqwest::StatusCode::OK {
+            return Err(anyhow::Error::new(RangeIgnored));
+        }
         let data = resp.bytes().await?;
-        self.data = data;
-        if self.data.len() != self.chunk_size {
-            anyhow::bail!(
-                "Expected {} bytes, got {}",
-                self.chunk_size,
-                self.data.len()
-            );
+        if data.len() != self.chunk_size {
+            anyhow::bail!("Expected {} bytes, got {}", self.chunk_size, data.len());
         }
-        anyhow::Ok(self)
+        Ok(data)
     }
 
     /// save one chunk to file
@@ -69,8 +105,81 @@ impl Chunk {
         client: &ClientWithMiddleware,
         url: &str,
         f: Arc<Mutex<File>>,
+        retry: usize,
     ) -> anyhow::Result<()> {
-        self.download(client, url).await?.save(f).await?;
+        self.download(client, url, retry).await?.save(f).await?;
         Ok(())
     }
+
+    /// stream one chunk straight to disk without buffering the whole range in
+    /// memory: each received frame is written at the running offset as it
+    /// arrives, so memory stays constant regardless of chunk size. Returns the
+    /// number of bytes written. `self.data` is left empty — use
+    /// [`Chunk::download`] for the opt-in buffered mode.
+    pub async fn stream_save(
+        self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        f: Arc<Mutex<File>>,
+        pb: ProgressBar,
+        retry: usize,
+    ) -> anyhow::Result<usize> {
+        let mut attempt = 0;
+        loop {
+            match self.stream_save_once(client, url, &f, &pb).await {
+                Ok(written) => return anyhow::Ok(written),
+                Err(e) if e.downcast_ref::<RangeIgnored>().is_some() || attempt >= retry => {
+                    return Err(e);
+                }
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+
+    async fn stream_save_once(
+        &self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        f: &Arc<Mutex<File>>,
+        pb: &ProgressBar,
+    ) -> anyhow::Result<usize> {
+        let resp = client
+            .get(url)
+            .header(
+                RANGE,
+                format!("bytes={}-{}", self.start, self.start + self.chunk_size - 1),
+            )
+            .send()
+            .await?;
+        if resp.status() == reqwest::StatusCode::OK {
+            return Err(anyhow::Error::new(RangeIgnored));
+        }
+        let mut stream = resp.bytes_stream();
+        let mut offset = self.start as u64;
+        let mut written = 0usize;
+        while let Some(frame) = stream.next().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // Rewind this attempt's contribution so a retry does not
+                    // double-count against the shared progress bar.
+                    pb.set_position(pb.position().saturating_sub(written as u64));
+                    return Err(e.into());
+                }
+            };
+            {
+                let mut file = f.lock().await;
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+                file.write_all(&frame).await?;
+            }
+            offset += frame.len() as u64;
+            written += frame.len();
+            pb.inc(frame.len() as u64);
+        }
+        if written != self.chunk_size {
+            pb.set_position(pb.position().saturating_sub(written as u64));
+            anyhow::bail!("Expected {} bytes, got {}", self.chunk_size, written);
+        }
+        anyhow::Ok(written)
+    }
 }
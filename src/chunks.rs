@@ -1,12 +1,29 @@
 use std::sync::Arc;
 
+use futures_util::StreamExt;
 use indicatif::ProgressBar;
-use reqwest::header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH};
+use reqwest::header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, ETAG, LAST_MODIFIED};
 use reqwest_middleware::ClientWithMiddleware;
-use tokio::{fs::File, sync::Mutex};
+use tokio::{
+    fs::File,
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
 
+use crate::archiver::ResumeState;
 use crate::chunk::Chunk;
 use crate::chunk_vec::ChunkVec;
+use crate::host_limiter::HostLimiter;
+
+/// Metadata gathered from a HEAD request, used both to plan the chunk layout
+/// and to detect that a cached resume archive has gone stale.
+#[derive(Debug, Clone)]
+pub struct RemoteInfo {
+    pub accept_ranges: bool,
+    pub total: usize,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Chunks {
@@ -33,23 +50,36 @@ impl Chunks {
         url: &str,
         workers: usize,
     ) -> anyhow::Result<Self> {
-        let (accept_ranges, total) = Self::get_content_length(client, url).await?;
-        if !accept_ranges || total < 1024 * 1024 * 4 {
-            Ok(Self {
+        let remote = Self::remote_info(client, url).await?;
+        Ok(Self::with_remote(&remote, workers))
+    }
+
+    /// Plan the chunk layout from already-fetched remote metadata. Small or
+    /// non-rangeable resources fall back to a single chunk spanning the file.
+    pub fn with_remote(remote: &RemoteInfo, workers: usize) -> Self {
+        let total = remote.total;
+        if !remote.accept_ranges || total < 1024 * 1024 * 4 {
+            Self {
                 start: 0,
                 total,
                 chunk_size: total,
-            })
+            }
         } else {
             let chunk_size = total / workers + 1;
-            Ok(Self {
+            Self {
                 start: 0,
                 total,
                 chunk_size,
-            })
+            }
         }
     }
 
+    /// The `(start, chunk_size)` pairs this plan will produce, without
+    /// consuming the iterator. Used to seed a resume archive.
+    pub fn layout(&self) -> Vec<(usize, usize)> {
+        (*self).map(|c| (c.start, c.chunk_size)).collect()
+    }
+
     /// download all chunk and then save them
     pub async fn download_then_save(
         self,
@@ -57,10 +87,15 @@ impl Chunks {
         url: &str,
         f: Arc<Mutex<File>>,
         pb: ProgressBar,
+        limiter: &HostLimiter,
+        retry: usize,
     ) -> anyhow::Result<()> {
         pb.set_length(self.total as u64);
         let f_clone = f.clone();
-        self.download(client, url).await?.save(f, pb).await?;
+        self.download(client, url, limiter, retry)
+            .await?
+            .save(f, pb)
+            .await?;
         self.verify(f_clone).await?;
         Ok(())
     }
@@ -72,41 +107,176 @@ impl Chunks {
         url: &str,
         f: Arc<Mutex<File>>,
         pb: ProgressBar,
+        limiter: &HostLimiter,
+        retry: usize,
+    ) -> anyhow::Result<()> {
+        self.download_and_save_resumable(client, url, f, pb, limiter, retry, None)
+            .await
+    }
+
+    /// download and save concurrently, optionally checkpointing progress so an
+    /// interrupted run can resume the chunks it had not yet completed. If the
+    /// ranged chunk downloads keep failing — or the server ignores `Range` and
+    /// answers with a full `200` — the chunk plan is abandoned and the whole
+    /// resource is streamed in a single request instead.
+    pub async fn download_and_save_resumable(
+        self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        f: Arc<Mutex<File>>,
+        pb: ProgressBar,
+        limiter: &HostLimiter,
+        retry: usize,
+        resume: Option<ResumeState>,
     ) -> anyhow::Result<()> {
         pb.set_length(self.total as u64);
-        let mut tasks = Vec::new();
+        if let Err(e) = self
+            .download_chunks(client, url, f.clone(), pb.clone(), limiter, retry, resume.clone())
+            .await
+        {
+            // Ranged downloads are unusable here; stream the whole file once.
+            self.stream_whole(client, url, f.clone(), pb.clone(), limiter, retry)
+                .await
+                .map_err(|_| e)?;
+        }
+        self.verify(f).await?;
+        if let Some(resume) = &resume {
+            resume.finish().await?;
+        }
+        Ok(())
+    }
+
+    /// Run the ranged chunk plan, spawning one task per outstanding chunk.
+    async fn download_chunks(
+        self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        f: Arc<Mutex<File>>,
+        pb: ProgressBar,
+        limiter: &HostLimiter,
+        retry: usize,
+        resume: Option<ResumeState>,
+    ) -> anyhow::Result<()> {
+        let mut tasks = tokio::task::JoinSet::new();
         for chunk in self {
+            let size = chunk.chunk_size;
+            // Skip chunks a previous run already wrote and synced.
+            if let Some(resume) = &resume {
+                if resume.is_done(chunk.start).await {
+                    pb.inc(size as u64);
+                    continue;
+                }
+            }
             let client = client.clone();
             let url = url.to_string();
             let f = f.clone();
             let pb = pb.clone();
-            tasks.push(tokio::spawn(async move {
-                let size = chunk.chunk_size;
-                chunk.download_and_save(&client, &url, f).await?;
-                pb.inc(size as u64);
+            let resume = resume.clone();
+            let limiter = limiter.clone();
+            tasks.spawn(async move {
+                let start = chunk.start;
+                let _permit = limiter.acquire(&url).await?;
+                let fsync = f.clone();
+                // Stream straight to disk so a multi-GB file never lands in RAM;
+                // the progress bar is advanced per received frame inside.
+                chunk.stream_save(&client, &url, f, pb, retry).await?;
+                if let Some(resume) = &resume {
+                    // Flush the chunk's bytes before recording it complete, so a
+                    // crash after the checkpoint rename can never leave a chunk
+                    // flagged done whose data is still only in the page cache.
+                    fsync.lock().await.sync_all().await?;
+                    resume.complete(start).await?;
+                }
                 anyhow::Ok(())
-            }))
+            });
         }
-        for task in tasks {
-            task.await??;
+        // Abort and drain every outstanding chunk task before returning an
+        // error, so a failing plan never leaves detached writers streaming into
+        // the file while the single-stream fallback rewrites it from offset 0
+        // (and never strands host-limiter permits that would block the fallback).
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    tasks.shutdown().await;
+                    return Err(e);
+                }
+                Err(e) if e.is_cancelled() => {}
+                Err(e) => {
+                    tasks.shutdown().await;
+                    return Err(e.into());
+                }
+            }
         }
-        self.verify(f).await?;
         Ok(())
     }
 
+    /// Abandon the chunk plan and stream the whole resource in a single,
+    /// unranged request, retrying the body up to `retry` times.
+    async fn stream_whole(
+        &self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        f: Arc<Mutex<File>>,
+        pb: ProgressBar,
+        limiter: &HostLimiter,
+        retry: usize,
+    ) -> anyhow::Result<()> {
+        let _permit = limiter.acquire(url).await?;
+        let mut attempt = 0;
+        loop {
+            match self.stream_whole_once(client, url, &f, &pb).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= retry => return Err(e),
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+
+    async fn stream_whole_once(
+        &self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        f: &Arc<Mutex<File>>,
+        pb: &ProgressBar,
+    ) -> anyhow::Result<()> {
+        let resp = client.get(url).send().await?;
+        let mut stream = resp.bytes_stream();
+        let mut written = 0usize;
+        pb.set_position(0);
+        {
+            let mut file = f.lock().await;
+            file.seek(std::io::SeekFrom::Start(0)).await?;
+            while let Some(frame) = stream.next().await {
+                let frame = frame?;
+                file.write_all(&frame).await?;
+                written += frame.len();
+                pb.set_position(written as u64);
+            }
+        }
+        if self.total != 0 && written != self.total {
+            anyhow::bail!("Expected {} bytes, got {}", self.total, written);
+        }
+        anyhow::Ok(())
+    }
+
     /// download all chunks
     pub async fn download(
         self,
         client: &ClientWithMiddleware,
         url: &str,
+        limiter: &HostLimiter,
+        retry: usize,
     ) -> anyhow::Result<ChunkVec> {
         let mut tasks = Vec::new();
         for chunk in self {
             let client = client.clone();
             let url = url.to_string();
-            tasks.push(tokio::spawn(
-                async move { chunk.download(&client, &url).await },
-            ))
+            let limiter = limiter.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = limiter.acquire(&url).await?;
+                chunk.download(&client, &url, retry).await
+            }))
         }
         let mut chunks = Vec::new();
         for task in tasks {
@@ -129,10 +299,12 @@ impl Chunks {
         anyhow::Ok(())
     }
 
-    async fn get_content_length(
+    /// Probe the remote resource with a HEAD request, collecting the headers
+    /// needed to size chunks and validate resume state.
+    pub async fn remote_info(
         client: &ClientWithMiddleware,
         url: &str,
-    ) -> anyhow::Result<(bool, usize)> {
+    ) -> anyhow::Result<RemoteInfo> {
         let resp = client.head(url).send().await?;
         let headers = resp.headers();
         let accept_ranges = headers
@@ -146,7 +318,20 @@ impl Chunks {
                 .and_then(|v| v.parse::<usize>().ok())
                 .unwrap_or(0)
         });
-        Ok((accept_ranges, total))
+
+        let header_str = |name: reqwest::header::HeaderName| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        Ok(RemoteInfo {
+            accept_ranges,
+            total,
+            etag: header_str(ETAG),
+            last_modified: header_str(LAST_MODIFIED),
+        })
     }
 }
 
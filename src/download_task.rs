@@ -4,6 +4,9 @@ use std::{path::PathBuf, str::FromStr};
 pub struct DownloadTask {
     pub url: String,
     pub filename: PathBuf,
+    /// Expected lower-case hex SHA-256 of the finished file, verified after the
+    /// byte-count check. `None` skips integrity verification.
+    pub expected_sha256: Option<String>,
 }
 
 impl DownloadTask {
@@ -11,6 +14,17 @@ impl DownloadTask {
         Self {
             url: url.to_string(),
             filename: PathBuf::from(filename),
+            expected_sha256: None,
+        }
+    }
+
+    /// Like [`DownloadTask::new`] but fails the download unless the finished
+    /// file hashes to `sha256` (lower-case hex).
+    pub fn with_checksum(url: &str, filename: &str, sha256: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            filename: PathBuf::from(filename),
+            expected_sha256: Some(sha256.to_string()),
         }
     }
 }
@@ -30,6 +44,7 @@ impl FromStr for DownloadTask {
         Ok(DownloadTask {
             url: s.to_string(),
             filename: PathBuf::from(filename),
+            expected_sha256: None,
         })
     }
 }
@@ -6,8 +6,10 @@ use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use tokio::sync::Mutex;
 
+use crate::archiver::{self, DownloadArchive, ResumeState};
 use crate::chunks::Chunks;
 use crate::download_task::DownloadTask;
+use crate::host_limiter::HostLimiter;
 
 pub enum DownloadPolicy {
     /// download all chunks and then save them to disk
@@ -23,6 +25,66 @@ pub struct Downloader {
     dir: PathBuf,
     client: ClientWithMiddleware,
     policy: DownloadPolicy,
+    resumable: bool,
+    part_suffix: String,
+    host_limit: usize,
+}
+
+/// Staging path a download is written to before being promoted onto its final
+/// name (`foo.tar.gz` -> `foo.tar.gz.part`). The suffix is appended rather than
+/// substituted so multi-dotted extensions survive.
+fn part_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = std::ffi::OsString::from(path.as_os_str());
+    s.push(".");
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+/// Outcome of a single finished download, reported back to the caller.
+#[derive(Debug, Clone)]
+pub struct DownloadOutcome {
+    pub filename: PathBuf,
+    /// Lower-case hex SHA-256 of the written file, computed for every download
+    /// so callers can record it even when no expected value was supplied.
+    pub sha256: String,
+}
+
+/// Re-read a finished file and compute its SHA-256, streaming it through the
+/// hasher so the check never materializes the whole file in memory.
+async fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncReadExt;
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Compute the staged file's SHA-256, fail on mismatch with an expected value,
+/// and return the outcome carrying the computed hash.
+async fn verified_outcome(
+    part: &Path,
+    filename: &Path,
+    expected: &Option<String>,
+) -> anyhow::Result<DownloadOutcome> {
+    let sha256 = sha256_file(part).await?;
+    if let Some(expected) = expected {
+        if !expected.eq_ignore_ascii_case(&sha256) {
+            anyhow::bail!("Checksum mismatch: expected {}, got {}", expected, sha256);
+        }
+    }
+    Ok(DownloadOutcome {
+        filename: filename.to_path_buf(),
+        sha256,
+    })
 }
 
 impl Default for Downloader {
@@ -44,21 +106,33 @@ impl Downloader {
         &self.client
     }
 
-    pub async fn download<T>(&self, tasks: T) -> anyhow::Result<()>
+    pub async fn download<T>(&self, tasks: T) -> anyhow::Result<Vec<DownloadOutcome>>
     where
         T: IntoIterator<Item = DownloadTask>,
     {
-        let mut futures: Vec<tokio::task::JoinHandle<anyhow::Result<()>>> = Vec::new();
+        let mut futures: Vec<tokio::task::JoinHandle<anyhow::Result<DownloadOutcome>>> = Vec::new();
         let m = MultiProgress::new();
+        // Shared across every task so the per-host cap holds even when many
+        // tasks target the same origin.
+        let limiter = HostLimiter::new(self.host_limit);
         let style =
             ProgressStyle::with_template("{elapsed:>3} [{bar:20.cyan/blue}] {percent:>3}% {msg:<}")
                 .unwrap()
                 .progress_chars("##-");
 
-        for DownloadTask { url, filename } in tasks {
+        for DownloadTask {
+            url,
+            filename,
+            expected_sha256,
+        } in tasks
+        {
             let client = self.client.clone();
             let workers = self.workers;
+            let resumable = self.resumable;
+            let retry = self.retry;
+            let limiter = limiter.clone();
             let path = self.dir.join(&filename);
+            let part = part_path(&path, &self.part_suffix);
 
             let pb = m.add(ProgressBar::new(u64::MAX));
             pb.set_style(style.clone());
@@ -68,19 +142,34 @@ impl Downloader {
                 DownloadPolicy::DownloadThenSave => tokio::spawn(async move {
                     let (chunks, file) = tokio::join!(
                         Chunks::new(&client, &url, workers),
-                        tokio::fs::File::create(path),
+                        tokio::fs::File::create(&part),
                     );
 
                     let chunks = chunks?;
                     let file = Arc::new(Mutex::new(file?));
 
                     match chunks
-                        .download_then_save(&client, &url, file, pb.clone())
+                        .download_then_save(&client, &url, file, pb.clone(), &limiter, retry)
                         .await
                     {
                         Ok(_) => {
+                            // Verify integrity on the staged file, then promote
+                            // it onto the real path only once it has verified.
+                            let outcome = match verified_outcome(&part, &filename, &expected_sha256)
+                                .await
+                            {
+                                Ok(outcome) => outcome,
+                                Err(e) => {
+                                    pb.finish_with_message(format!(
+                                        "{} \u{274C}",
+                                        filename.display()
+                                    ));
+                                    return Err(e);
+                                }
+                            };
+                            tokio::fs::rename(&part, &path).await?;
                             pb.finish_with_message(format!("{} \u{2705}", filename.display()));
-                            anyhow::Ok(())
+                            anyhow::Ok(outcome)
                         }
                         Err(e) => {
                             pb.finish_with_message(format!("{} \u{274C}", filename.display()));
@@ -89,21 +178,77 @@ impl Downloader {
                     }
                 }),
                 DownloadPolicy::DownloadAndSave => tokio::spawn(async move {
-                    let (chunks, file) = tokio::join!(
-                        Chunks::new(&client, &url, workers),
-                        tokio::fs::File::create(path),
-                    );
+                    let remote = Chunks::remote_info(&client, &url).await?;
+                    let chunks = Chunks::with_remote(&remote, workers);
 
-                    let chunks = chunks?;
+                    // Load any existing checkpoint and, if it still matches the
+                    // remote resource, resume the chunks it had not finished.
+                    let (resume, resumed_existing) = if resumable {
+                        let apath = archiver::archive_path(&part);
+                        let loaded = archiver::load(&apath).await.filter(|a| {
+                            a.matches(remote.total, &remote.etag, &remote.last_modified)
+                        });
+                        let resumed_existing = loaded.is_some();
+                        let archive = loaded.unwrap_or_else(|| {
+                            DownloadArchive::new(
+                                remote.total,
+                                remote.etag.clone(),
+                                remote.last_modified.clone(),
+                                chunks.layout(),
+                            )
+                        });
+                        (Some(ResumeState::new(archive, apath)), resumed_existing)
+                    } else {
+                        (None, false)
+                    };
+
+                    // Resume must preserve already-written bytes, so open the
+                    // file without truncating only when resuming a checkpoint
+                    // that still matches the remote. A fresh or discarded
+                    // archive truncates, so stale trailing bytes from a larger
+                    // previous resource never survive into `verify`.
+                    let file = if resume.is_some() {
+                        tokio::fs::OpenOptions::new()
+                            .create(true)
+                            .write(true)
+                            .truncate(!resumed_existing)
+                            .open(&part)
+                            .await
+                    } else {
+                        tokio::fs::File::create(&part).await
+                    };
                     let file = Arc::new(Mutex::new(file?));
 
                     match chunks
-                        .download_and_save(&client, &url, file, pb.clone())
+                        .download_and_save_resumable(
+                            &client,
+                            &url,
+                            file,
+                            pb.clone(),
+                            &limiter,
+                            retry,
+                            resume,
+                        )
                         .await
                     {
                         Ok(_) => {
+                            // Verify integrity on the staged file, then promote
+                            // it onto the real path only once it has verified.
+                            let outcome = match verified_outcome(&part, &filename, &expected_sha256)
+                                .await
+                            {
+                                Ok(outcome) => outcome,
+                                Err(e) => {
+                                    pb.finish_with_message(format!(
+                                        "{} \u{274C}",
+                                        filename.display()
+                                    ));
+                                    return Err(e);
+                                }
+                            };
+                            tokio::fs::rename(&part, &path).await?;
                             pb.finish_with_message(format!("{} \u{2705}", filename.display()));
-                            anyhow::Ok(())
+                            anyhow::Ok(outcome)
                         }
                         Err(e) => {
                             pb.finish_with_message(format!("{} \u{274C}", filename.display()));
@@ -115,10 +260,11 @@ impl Downloader {
 
             futures.push(task);
         }
+        let mut outcomes = Vec::new();
         for future in futures {
-            future.await??;
+            outcomes.push(future.await??);
         }
-        anyhow::Ok(())
+        anyhow::Ok(outcomes)
     }
 }
 
@@ -128,6 +274,9 @@ pub struct DownloaderBuilder {
     retry: Option<usize>,
     dir: Option<PathBuf>,
     policy: Option<DownloadPolicy>,
+    resumable: Option<bool>,
+    part_suffix: Option<String>,
+    host_limit: Option<usize>,
 }
 
 impl DownloaderBuilder {
@@ -135,6 +284,9 @@ impl DownloaderBuilder {
     const DEFAULT_RETRY: usize = 2;
     const DEFAULT_DIR: &str = "./";
     const DEFAULT_POLICY: DownloadPolicy = DownloadPolicy::DownloadAndSave;
+    const DEFAULT_RESUMABLE: bool = false;
+    const DEFAULT_PART_SUFFIX: &str = "part";
+    const DEFAULT_HOST_LIMIT: usize = 6;
 
     pub fn new() -> Self {
         Self::default()
@@ -160,6 +312,27 @@ impl DownloaderBuilder {
         self
     }
 
+    /// Resume interrupted downloads from an on-disk checkpoint rather than
+    /// restarting from zero. Only honored by [`DownloadPolicy::DownloadAndSave`].
+    pub fn resumable(mut self, resumable: bool) -> Self {
+        self.resumable = Some(resumable);
+        self
+    }
+
+    /// Suffix for the staging file a download is written to before being
+    /// renamed onto its final name (defaults to `part`).
+    pub fn part_suffix<S: Into<String>>(mut self, suffix: S) -> Self {
+        self.part_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Maximum number of concurrent chunk requests allowed against any single
+    /// host (defaults to 6), regardless of the overall worker count.
+    pub fn host_limit(mut self, host_limit: usize) -> Self {
+        self.host_limit = Some(host_limit);
+        self
+    }
+
     pub fn build(self) -> Downloader {
         Downloader {
             client: self.build_client(self.retry),
@@ -167,6 +340,11 @@ impl DownloaderBuilder {
             workers: self.workers.unwrap_or(Self::DEFAULT_WORKERS),
             retry: self.retry.unwrap_or(Self::DEFAULT_RETRY),
             dir: self.dir.unwrap_or(PathBuf::from(Self::DEFAULT_DIR)),
+            resumable: self.resumable.unwrap_or(Self::DEFAULT_RESUMABLE),
+            part_suffix: self
+                .part_suffix
+                .unwrap_or_else(|| Self::DEFAULT_PART_SUFFIX.to_string()),
+            host_limit: self.host_limit.unwrap_or(Self::DEFAULT_HOST_LIMIT),
         }
     }
 
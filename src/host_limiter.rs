@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+
+/// Caps the number of concurrent requests issued against any single host.
+///
+/// A high overall `workers` count keeps total throughput up, but hammering one
+/// origin with all of it invites rate-limiting or dropped connections. Each
+/// chunk GET acquires a permit keyed by the request's host first, so at most
+/// `limit` requests run against that host at once.
+#[derive(Clone)]
+pub struct HostLimiter {
+    limit: usize,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl HostLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Acquire a permit for the host of `url`, lazily creating that host's
+    /// semaphore on first use. Returns `None` when the url carries no host and
+    /// there is nothing to limit.
+    pub async fn acquire(&self, url: &str) -> anyhow::Result<Option<OwnedSemaphorePermit>> {
+        let host = match reqwest::Url::parse(url)?.host_str() {
+            Some(host) => host.to_string(),
+            None => return Ok(None),
+        };
+        let sem = {
+            let mut hosts = self.hosts.lock().await;
+            hosts
+                .entry(host)
+                .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+                .clone()
+        };
+        Ok(Some(sem.acquire_owned().await?))
+    }
+}
@@ -1,12 +1,14 @@
 #![allow(unused)]
 
+pub mod archiver;
 pub mod chunk;
 pub mod chunk_vec;
 pub mod chunks;
 pub mod download_task;
 pub mod downloader;
+pub mod host_limiter;
 
 pub mod prelude {
     pub use crate::download_task::DownloadTask;
-    pub use crate::downloader::{DownloadPolicy, Downloader, DownloaderBuilder};
+    pub use crate::downloader::{DownloadOutcome, DownloadPolicy, Downloader, DownloaderBuilder};
 }
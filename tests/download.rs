@@ -15,3 +15,103 @@ async fn test_download_then_save() -> anyhow::Result<()> {
 
     anyhow::Ok(())
 }
+
+use mini_downloader::archiver::{DownloadArchive, ResumeState};
+use mini_downloader::chunks::{Chunks, RemoteInfo};
+use mini_downloader::host_limiter::HostLimiter;
+
+#[test]
+fn archive_matches_detects_staleness() {
+    let archive = DownloadArchive::new(
+        1000,
+        Some("\"abc\"".to_string()),
+        Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        [(0, 500), (500, 500)],
+    );
+
+    // Same size and ETag -> still valid.
+    assert!(archive.matches(1000, &Some("\"abc\"".to_string()), &None));
+    // Changed length -> stale.
+    assert!(!archive.matches(2000, &Some("\"abc\"".to_string()), &None));
+    // Changed ETag -> stale.
+    assert!(!archive.matches(1000, &Some("\"xyz\"".to_string()), &None));
+    // No validators to compare -> fall back to trusting the length match.
+    assert!(archive.matches(1000, &None, &None));
+}
+
+#[test]
+fn archive_tracks_done_chunks() {
+    let mut archive = DownloadArchive::new(1000, None, None, [(0, 500), (500, 500)]);
+    assert!(!archive.is_done(0));
+    assert!(!archive.all_done());
+
+    archive.mark_done(0);
+    assert!(archive.is_done(0));
+    assert!(!archive.all_done());
+
+    archive.mark_done(500);
+    assert!(archive.all_done());
+}
+
+#[test]
+fn layout_seeds_contiguous_chunks() {
+    let remote = RemoteInfo {
+        accept_ranges: true,
+        total: 10 * 1024 * 1024,
+        etag: None,
+        last_modified: None,
+    };
+    let layout = Chunks::with_remote(&remote, 4).layout();
+
+    assert!(!layout.is_empty());
+    assert_eq!(layout[0].0, 0);
+    // Chunks tile the whole resource without gaps or overlap.
+    let mut offset = 0;
+    for (start, size) in &layout {
+        assert_eq!(*start, offset);
+        offset += size;
+    }
+    assert_eq!(offset, remote.total);
+}
+
+#[tokio::test]
+async fn concurrent_complete_persists_all_chunks() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join("mdpart_concurrent_test");
+    tokio::fs::create_dir_all(&dir).await?;
+    let apath = dir.join("concurrent.bin.part.mdpart.json");
+    let _ = tokio::fs::remove_file(&apath).await;
+
+    let layout: Vec<(usize, usize)> = (0..16).map(|i| (i * 100, 100)).collect();
+    let archive = DownloadArchive::new(1600, None, None, layout.clone());
+    let resume = ResumeState::new(archive, apath.clone());
+
+    // Checkpoint every chunk from its own task at once; each write must succeed
+    // despite sharing the single temp staging path.
+    let mut tasks = tokio::task::JoinSet::new();
+    for (start, _) in layout {
+        let resume = resume.clone();
+        tasks.spawn(async move { resume.complete(start).await });
+    }
+    while let Some(res) = tasks.join_next().await {
+        res??;
+    }
+
+    // The reloaded sidecar reflects every completion, with none lost to a race.
+    let persisted = mini_downloader::archiver::load(&apath)
+        .await
+        .expect("archive persisted");
+    assert!(persisted.all_done());
+
+    resume.finish().await?;
+    anyhow::Ok(())
+}
+
+#[tokio::test]
+async fn host_limiter_skips_hostless_urls() {
+    let limiter = HostLimiter::new(2);
+    assert_eq!(limiter.limit(), 2);
+    // A permit for a real host is handed out.
+    assert!(limiter.acquire("https://example.com/file").await.unwrap().is_some());
+    // A url with no host has nothing to limit.
+    assert!(limiter.acquire("file:///tmp/file").await.unwrap().is_none());
+}